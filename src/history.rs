@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::params;
+
+use crate::Error;
+
+/// One executed query, as recorded in `history`.
+pub struct HistoryEntry {
+    pub query: String,
+    pub executed_at: String,
+    pub duration_ms: i64,
+}
+
+/// Pooled handle onto the bot's SQLite database. Cheap to clone: `Pool`
+/// shares its connections behind an `Arc` internally.
+#[derive(Clone)]
+pub struct HistoryStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl HistoryStore {
+    /// Returns a clone of the underlying pool, so other stores (e.g. saved
+    /// queries) can share the same SQLite database.
+    pub fn pool(&self) -> Pool<SqliteConnectionManager> {
+        self.pool.clone()
+    }
+
+    /// Opens (and creates, if missing) the SQLite database at `path` and
+    /// ensures the `history` table exists.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let manager = SqliteConnectionManager::file(path);
+        let pool = Pool::new(manager)?;
+        pool.get()?.execute(
+            "CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                query TEXT NOT NULL,
+                executed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                duration_ms INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(Self { pool })
+    }
+
+    /// Records a successfully executed query for `user_id`. `duration` may be
+    /// `Duration::ZERO` for a query served from cache.
+    pub fn record(&self, user_id: u64, query: &str, duration: Duration) -> Result<(), Error> {
+        self.pool.get()?.execute(
+            "INSERT INTO history (user_id, query, duration_ms) VALUES (?1, ?2, ?3)",
+            params![user_id as i64, query, duration.as_millis() as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Returns `user_id`'s most recent queries, newest first.
+    pub fn recent(&self, user_id: u64, limit: u32) -> Result<Vec<HistoryEntry>, Error> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT query, executed_at, duration_ms FROM history
+             WHERE user_id = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt
+            .query_map(params![user_id as i64, limit], |row| {
+                Ok(HistoryEntry {
+                    query: row.get(0)?,
+                    executed_at: row.get(1)?,
+                    duration_ms: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+
+    /// Returns the `n`th most recent query for `user_id` (1 = most recent),
+    /// matching the numbering shown by `/history`.
+    pub fn nth_most_recent(&self, user_id: u64, n: u32) -> Result<Option<String>, Error> {
+        if n == 0 {
+            return Ok(None);
+        }
+        let conn = self.pool.get()?;
+        let query = conn
+            .query_row(
+                "SELECT query FROM history WHERE user_id = ?1
+                 ORDER BY id DESC LIMIT 1 OFFSET ?2",
+                params![user_id as i64, n - 1],
+                |row| row.get(0),
+            )
+            .ok();
+        Ok(query)
+    }
+}
@@ -1,16 +1,33 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use anyhow::Result;
 use clap::Parser as CliParser;
 use clap_verbosity_flag::{InfoLevel, Verbosity};
 use poise::serenity_prelude as serenity;
 use regex::Regex;
 use reqwest::{Client, Response};
-use tabled::settings::Style;
 use tracing::info;
 use url::{ParseError, Url};
 
+mod history;
+mod pagination;
+mod saved_queries;
+mod subscriptions;
+
+use history::HistoryStore;
+use saved_queries::SavedQueryStore;
+use subscriptions::Subscriptions;
+
 struct Data {
     url: String,
     output_limit: String,
+    subscriptions: Arc<Subscriptions>,
+    http: Client,
+    cache: moka::future::Cache<String, String>,
+    allowed_roles: Vec<serenity::RoleId>,
+    history: HistoryStore,
+    saved_queries: SavedQueryStore,
 }
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
@@ -38,6 +55,18 @@ struct Cli {
     #[arg(long, default_value = "10")]
     output_limit: String,
 
+    /// How long a query result stays cached, in seconds
+    #[arg(long, default_value = "60")]
+    cache_ttl: u64,
+
+    /// Discord role IDs allowed to run queries. Leave empty to allow everyone.
+    #[arg(long, value_delimiter = ',')]
+    allowed_roles: Vec<u64>,
+
+    /// Path to the SQLite database used for query history
+    #[arg(long, default_value = "chbot_history.sqlite")]
+    history_db: String,
+
     /// Verbosity level
     #[command(flatten)]
     verbose: Verbosity<InfoLevel>,
@@ -60,7 +89,54 @@ async fn format_url(cli: &Cli) -> Result<String, ParseError> {
     Ok(url.join(&qs)?.to_string())
 }
 
-async fn format_query(query: String, output_limit: i32) -> Result<String, Error> {
+/// Statement keywords a query is allowed to start with. Anything else
+/// (`INSERT`, `ALTER`, `DROP`, ...) can mutate or drop data and is rejected.
+const ALLOWED_LEADING_KEYWORDS: [&str; 4] = ["SELECT", "WITH", "SHOW", "DESCRIBE"];
+
+/// Whether `body` contains a `;` outside of a single-quoted string literal
+/// (with `''` treated as an escaped quote, as ClickHouse/SQL do), meaning
+/// it chains more than one statement.
+fn has_unquoted_semicolon(body: &str) -> bool {
+    let mut in_string = false;
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' if in_string && chars.peek() == Some(&'\'') => {
+                chars.next();
+            }
+            '\'' => in_string = !in_string,
+            ';' if !in_string => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Reject anything that isn't a single read-only statement: a leading
+/// keyword outside `ALLOWED_LEADING_KEYWORDS`, or more than one statement
+/// chained with `;`.
+fn validate_query(query: &str) -> Result<(), Error> {
+    let trimmed = query.trim();
+    let body = trimmed.strip_suffix(';').unwrap_or(trimmed);
+    if has_unquoted_semicolon(body) {
+        return Err("Only a single statement is allowed".into());
+    }
+
+    let leading_keyword = body.split_whitespace().next().unwrap_or("").to_uppercase();
+    if !ALLOWED_LEADING_KEYWORDS.contains(&leading_keyword.as_str()) {
+        return Err(format!(
+            "Only {} statements are allowed",
+            ALLOWED_LEADING_KEYWORDS.join("/")
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn format_query(query: String, output_limit: i32) -> Result<String, Error> {
+    validate_query(&query)?;
+
     let mut formatted_query = query.clone();
 
     let re = Regex::new(r".*LIMIT\s(\d+).*$").unwrap();
@@ -91,25 +167,88 @@ async fn format_query(query: String, output_limit: i32) -> Result<String, Error>
     Ok(formatted_query)
 }
 
-async fn do_query(url: String, query: String) -> Result<Response, Error> {
-    let client = Client::new();
+pub(crate) async fn do_query(
+    client: &Client,
+    url: String,
+    query: String,
+) -> Result<(Response, Duration), Error> {
     let time_start = std::time::Instant::now();
     let resp = client.post(url).body(query.clone()).send().await?;
-    let time_end = std::time::Instant::now();
-    let time_diff = time_end - time_start;
+    let time_diff = time_start.elapsed();
     info!("`{}` took {:?}", query, time_diff);
-    Ok(resp)
+    Ok((resp, time_diff))
+}
+
+/// Checks the invoking member's roles against `Data::allowed_roles`. An empty
+/// allow-list means the role gate is disabled and everyone is permitted.
+async fn has_query_permission(ctx: &Context<'_>) -> Result<bool, Error> {
+    let allowed_roles = &ctx.data().allowed_roles;
+    if allowed_roles.is_empty() {
+        return Ok(true);
+    }
+
+    let member = match ctx.author_member().await {
+        Some(member) => member,
+        None => return Ok(false),
+    };
+    Ok(member.roles.iter().any(|role| allowed_roles.contains(role)))
 }
 
-async fn pretty_print(text: String) -> String {
-    let table = csv_to_table::from_reader(text.as_bytes())
-        .unwrap()
-        .with(Style::sharp())
-        .to_string();
+/// Runs `raw_query` through the format/validate, cache, and execution
+/// pipeline shared by `/query`, `/rerun` and `/run`, then records it to history.
+async fn run_query_pipeline(ctx: Context<'_>, raw_query: String) -> Result<(), Error> {
+    let no_cache = raw_query.starts_with("nocache:");
+    let raw_query = raw_query.trim_start_matches("nocache:").trim().to_string();
+
+    let output_limit: i32 = ctx.data().output_limit.clone().parse().unwrap();
+    let query_text = match format_query(raw_query.clone(), output_limit).await {
+        Ok(query_text) => query_text,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    ctx.defer().await?;
+
+    let csv_text = if !no_cache {
+        match ctx.data().cache.get(&query_text).await {
+            Some(csv_text) => {
+                ctx.data()
+                    .history
+                    .record(ctx.author().id.get(), &raw_query, Duration::ZERO)?;
+                csv_text
+            }
+            None => {
+                let (resp, duration) = do_query(&ctx.data().http, ctx.data().url.clone(), query_text.clone()).await?;
+                let status = resp.status();
+                let csv_text = resp.text().await?;
+                if !status.is_success() {
+                    ctx.say(format!("Query failed: {}", csv_text)).await?;
+                    return Ok(());
+                }
+                ctx.data().cache.insert(query_text.clone(), csv_text.clone()).await;
+                ctx.data()
+                    .history
+                    .record(ctx.author().id.get(), &raw_query, duration)?;
+                csv_text
+            }
+        }
+    } else {
+        let (resp, duration) = do_query(&ctx.data().http, ctx.data().url.clone(), query_text.clone()).await?;
+        let status = resp.status();
+        let csv_text = resp.text().await?;
+        if !status.is_success() {
+            ctx.say(format!("Query failed: {}", csv_text)).await?;
+            return Ok(());
+        }
+        ctx.data()
+            .history
+            .record(ctx.author().id.get(), &raw_query, duration)?;
+        csv_text
+    };
 
-    // Return the table in a code block
-    // This will make it look nice in Discord
-    format!("```{}```", table)
+    pagination::send_table(ctx, csv_text).await
 }
 
 #[poise::command(slash_command, prefix_command)]
@@ -117,6 +256,11 @@ async fn query(
     ctx: Context<'_>,
     #[description = "Query"] query_text: Option<String>,
 ) -> Result<(), Error> {
+    if !has_query_permission(&ctx).await? {
+        ctx.say("You don't have a role allowed to run queries").await?;
+        return Ok(());
+    }
+
     let query_text = match query_text {
         Some(query_text) => query_text,
         None => {
@@ -125,24 +269,197 @@ async fn query(
         }
     };
 
+    run_query_pipeline(ctx, query_text).await
+}
+
+#[poise::command(slash_command, prefix_command)]
+async fn subscribe(
+    ctx: Context<'_>,
+    #[description = "Query"] query: String,
+    #[description = "Interval in seconds"] interval_secs: u64,
+) -> Result<(), Error> {
+    if !has_query_permission(&ctx).await? {
+        ctx.say("You don't have a role allowed to run queries").await?;
+        return Ok(());
+    }
+
+    if interval_secs == 0 {
+        ctx.say("Interval must be greater than 0 seconds").await?;
+        return Ok(());
+    }
+
     let output_limit: i32 = ctx.data().output_limit.clone().parse().unwrap();
-    let query_text = match format_query(query_text, output_limit).await {
-        Ok(query_text) => query_text,
-        Err(e) => {
-            ctx.say(format!("{}", e)).await?;
+    if let Err(e) = format_query(query.clone(), output_limit).await {
+        ctx.say(format!("{}", e)).await?;
+        return Ok(());
+    }
+
+    let id = ctx
+        .data()
+        .subscriptions
+        .add(ctx.channel_id(), query.clone(), Duration::from_secs(interval_secs))
+        .await;
+    ctx.say(format!(
+        "Subscribed as `{}`: `{}` every {}s",
+        id, query, interval_secs
+    ))
+    .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, prefix_command)]
+async fn unsubscribe(ctx: Context<'_>, #[description = "Subscription id"] id: u64) -> Result<(), Error> {
+    if !has_query_permission(&ctx).await? {
+        ctx.say("You don't have a role allowed to run queries").await?;
+        return Ok(());
+    }
+
+    if ctx.data().subscriptions.remove(id).await {
+        ctx.say(format!("Unsubscribed `{}`", id)).await?;
+    } else {
+        ctx.say(format!("No subscription with id `{}`", id)).await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, prefix_command, rename = "subscriptions")]
+async fn subscriptions_cmd(ctx: Context<'_>) -> Result<(), Error> {
+    let subs = ctx.data().subscriptions.list().await;
+    if subs.is_empty() {
+        ctx.say("No active subscriptions in this bot").await?;
+        return Ok(());
+    }
+
+    let mut text = String::from("Active subscriptions:\n");
+    for sub in subs {
+        text.push_str(&format!(
+            "`{}` in <#{}>: `{}` every {:?}\n",
+            sub.id, sub.channel_id, sub.query, sub.interval
+        ));
+    }
+    ctx.say(text).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, prefix_command)]
+async fn history(ctx: Context<'_>, #[description = "How many entries"] limit: Option<u32>) -> Result<(), Error> {
+    let entries = ctx
+        .data()
+        .history
+        .recent(ctx.author().id.get(), limit.unwrap_or(10))?;
+    if entries.is_empty() {
+        ctx.say("No query history yet").await?;
+        return Ok(());
+    }
+
+    let mut text = String::from("```\n");
+    for (n, entry) in entries.iter().enumerate() {
+        text.push_str(&format!(
+            "{}. [{}, {}ms] {}\n",
+            n + 1,
+            entry.executed_at,
+            entry.duration_ms,
+            entry.query
+        ));
+    }
+    text.push_str("```");
+    ctx.say(text).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, prefix_command)]
+async fn rerun(ctx: Context<'_>, #[description = "Entry number from /history"] n: u32) -> Result<(), Error> {
+    if !has_query_permission(&ctx).await? {
+        ctx.say("You don't have a role allowed to run queries").await?;
+        return Ok(());
+    }
+
+    let query_text = ctx.data().history.nth_most_recent(ctx.author().id.get(), n)?;
+    let query_text = match query_text {
+        Some(query_text) => query_text,
+        None => {
+            ctx.say(format!("No history entry `{}`", n)).await?;
             return Ok(());
         }
     };
 
-    ctx.defer().await?;
+    run_query_pipeline(ctx, query_text).await
+}
 
-    let resp = do_query(ctx.data().url.clone(), query_text).await?;
-    let text = pretty_print(resp.text().await?).await;
+#[poise::command(slash_command, prefix_command)]
+async fn save(ctx: Context<'_>, #[description = "Name"] name: String, #[description = "Query"] query: String) -> Result<(), Error> {
+    ctx.data().saved_queries.save(ctx.author().id.get(), &name, &query)?;
+    ctx.say(format!("Saved `{}`", name)).await?;
+    Ok(())
+}
 
+#[poise::command(slash_command, prefix_command)]
+async fn unsave(ctx: Context<'_>, #[description = "Name"] name: String) -> Result<(), Error> {
+    if ctx.data().saved_queries.unsave(ctx.author().id.get(), &name)? {
+        ctx.say(format!("Unsaved `{}`", name)).await?;
+    } else {
+        ctx.say(format!("No saved query named `{}`", name)).await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command, prefix_command, rename = "queries")]
+async fn queries_cmd(ctx: Context<'_>) -> Result<(), Error> {
+    let saved = ctx.data().saved_queries.list(ctx.author().id.get())?;
+    if saved.is_empty() {
+        ctx.say("No saved queries").await?;
+        return Ok(());
+    }
+
+    let mut text = String::from("```\n");
+    for (name, query) in saved {
+        text.push_str(&format!("{}: {}\n", name, query));
+    }
+    text.push_str("```");
     ctx.say(text).await?;
     Ok(())
 }
 
+#[poise::command(slash_command, prefix_command, rename = "run")]
+async fn run_cmd(
+    ctx: Context<'_>,
+    #[description = "Saved query name"] name: String,
+    #[description = "key=value arguments"]
+    #[rest]
+    args: Option<String>,
+) -> Result<(), Error> {
+    if !has_query_permission(&ctx).await? {
+        ctx.say("You don't have a role allowed to run queries").await?;
+        return Ok(());
+    }
+
+    let template = match ctx.data().saved_queries.get(ctx.author().id.get(), &name)? {
+        Some(template) => template,
+        None => {
+            ctx.say(format!("No saved query named `{}`", name)).await?;
+            return Ok(());
+        }
+    };
+
+    let parsed_args = match saved_queries::parse_args(args.as_deref().unwrap_or("")) {
+        Ok(parsed_args) => parsed_args,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    let query_text = match saved_queries::substitute(&template, &parsed_args) {
+        Ok(query_text) => query_text,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    run_query_pipeline(ctx, query_text).await
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -153,15 +470,54 @@ async fn main() -> Result<()> {
 
     let framework = poise::Framework::builder()
         .options(poise::FrameworkOptions {
-            commands: vec![query()],
+            commands: vec![
+                query(),
+                subscribe(),
+                unsubscribe(),
+                subscriptions_cmd(),
+                history(),
+                rerun(),
+                save(),
+                unsave(),
+                queries_cmd(),
+                run_cmd(),
+            ],
             ..Default::default()
         })
         .setup(|ctx, _ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+
+                let output_limit: i32 = cli.output_limit.parse()?;
+                let http_client = Client::new();
+                let cache = moka::future::Cache::builder()
+                    .time_to_live(Duration::from_secs(cli.cache_ttl))
+                    .build();
+
+                let subscriptions = Arc::new(Subscriptions::new());
+                tokio::spawn(subscriptions::run_subscriptions(
+                    ctx.http.clone(),
+                    http_client.clone(),
+                    subscriptions.clone(),
+                    url.clone(),
+                    output_limit,
+                ));
+
+                let allowed_roles = cli.allowed_roles.iter().copied().map(serenity::RoleId::new).collect();
+
+                let history = HistoryStore::open(&cli.history_db)?;
+
+                let saved_queries = SavedQueryStore::new(history.pool())?;
+
                 Ok(Data {
                     url,
                     output_limit: cli.output_limit,
+                    subscriptions,
+                    http: http_client,
+                    cache,
+                    allowed_roles,
+                    history,
+                    saved_queries,
                 })
             })
         })
@@ -215,4 +571,39 @@ mod tests {
         .await
         .is_err());
     }
+
+    #[tokio::test]
+    async fn test_format_query_rejects_non_read_only() {
+        assert!(format_query("DROP TABLE nxthdr.bgp_updates".to_string(), 10)
+            .await
+            .is_err());
+
+        assert!(format_query("INSERT INTO nxthdr.bgp_updates VALUES (1)".to_string(), 10)
+            .await
+            .is_err());
+
+        assert!(format_query(
+            "SELECT 1 FROM nxthdr.bgp_updates; DROP TABLE nxthdr.bgp_updates".to_string(),
+            10
+        )
+        .await
+        .is_err());
+
+        assert!(format_query("SHOW TABLES".to_string(), 10).await.is_ok());
+    }
+
+    #[test]
+    fn test_has_unquoted_semicolon_ignores_string_literals() {
+        assert!(!has_unquoted_semicolon("SELECT 'a;b'"));
+        assert!(!has_unquoted_semicolon("SELECT 'it''s; fine'"));
+        assert!(has_unquoted_semicolon("SELECT 1; DROP TABLE x"));
+    }
+
+    #[tokio::test]
+    async fn test_format_query_allows_semicolon_in_string_literal() {
+        assert_eq!(
+            format_query("SELECT 'a;b'".to_string(), 10).await.unwrap(),
+            "SELECT 'a;b' LIMIT 10 FORMAT CSVWithNames".to_string()
+        );
+    }
 }
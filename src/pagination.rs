@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use csv::{ReaderBuilder, WriterBuilder};
+use poise::serenity_prelude as serenity;
+use tabled::settings::Style;
+
+use crate::{Context, Error};
+
+/// Discord's hard cap on a single message's content.
+pub(crate) const DISCORD_MESSAGE_LIMIT: usize = 2000;
+/// Above this many rows a paginated table would run to dozens of pages, so
+/// we give up on rendering one and attach the raw CSV instead.
+pub(crate) const MAX_ROWS_BEFORE_FILE: usize = 500;
+
+pub(crate) fn parse_csv(csv_text: &str) -> Result<(Vec<String>, Vec<Vec<String>>), Error> {
+    let mut reader = ReaderBuilder::new().from_reader(csv_text.as_bytes());
+    let header = reader.headers()?.iter().map(str::to_string).collect();
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        rows.push(record?.iter().map(str::to_string).collect());
+    }
+    Ok((header, rows))
+}
+
+/// Renders `rows` as a code-blocked table, with `header` repeated at the top.
+pub(crate) fn render_rows(header: &[String], rows: &[Vec<String>]) -> Result<String, Error> {
+    let mut writer = WriterBuilder::new().from_writer(vec![]);
+    writer.write_record(header)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    let csv_bytes = writer.into_inner()?;
+
+    let table = csv_to_table::from_reader(csv_bytes.as_slice())?
+        .with(Style::sharp())
+        .to_string();
+    Ok(format!("```{}```", table))
+}
+
+/// Splits `rows` into chunks whose rendered table (with `header` repeated on
+/// each) stays within Discord's message limit. Each chunk is found by halving
+/// the remaining rows until they fit, so the number of pages is not
+/// necessarily the smallest possible. A single row whose
+/// own rendering already exceeds the limit is still returned as its own
+/// (oversized) page; callers should fall back to a file attachment in that
+/// case instead of posting it.
+pub(crate) fn paginate(header: &[String], rows: &[Vec<String>]) -> Result<Vec<String>, Error> {
+    if rows.is_empty() {
+        return Ok(vec![render_rows(header, rows)?]);
+    }
+
+    let mut pages = Vec::new();
+    let mut start = 0;
+    while start < rows.len() {
+        let mut end = rows.len();
+        loop {
+            let page = render_rows(header, &rows[start..end])?;
+            if page.len() <= DISCORD_MESSAGE_LIMIT || end - start <= 1 {
+                pages.push(page);
+                break;
+            }
+            end = start + (end - start) / 2;
+        }
+        start = end;
+    }
+    Ok(pages)
+}
+
+/// Result of laying a CSV result out for Discord: either it fits on one or
+/// more text pages, or it (or a single row within it) is too large to page
+/// through sensibly and should be attached as a raw CSV file instead.
+pub(crate) enum Rendered {
+    Pages(Vec<String>),
+    TooLargeForPages { row_count: usize },
+}
+
+/// Decides how to lay out `csv_text` for posting: as text pages under
+/// Discord's message limit, or as a signal to fall back to a file
+/// attachment (row count too high, or a single row too wide to paginate).
+pub(crate) fn render(csv_text: &str) -> Result<Rendered, Error> {
+    let (header, rows) = parse_csv(csv_text)?;
+
+    if rows.len() > MAX_ROWS_BEFORE_FILE {
+        return Ok(Rendered::TooLargeForPages { row_count: rows.len() });
+    }
+
+    let pages = paginate(&header, &rows)?;
+
+    // A single wide row can still render over the limit even though the row
+    // count is small; paginate() can't split it any further, so fall back.
+    if pages.iter().any(|page| page.len() > DISCORD_MESSAGE_LIMIT) {
+        return Ok(Rendered::TooLargeForPages { row_count: rows.len() });
+    }
+
+    Ok(Rendered::Pages(pages))
+}
+
+/// Sends a CSV query result, working around Discord's 2000-character
+/// message limit: a table that fits is sent as-is, one that doesn't is
+/// paginated with next/prev buttons, and one too large to page through
+/// sensibly is attached as a raw CSV file instead.
+pub async fn send_table(ctx: Context<'_>, csv_text: String) -> Result<(), Error> {
+    let mut pages = match render(&csv_text)? {
+        Rendered::TooLargeForPages { row_count } => return send_as_file(ctx, row_count, csv_text).await,
+        Rendered::Pages(pages) => pages,
+    };
+
+    if pages.len() <= 1 {
+        ctx.say(pages.pop().unwrap_or_default()).await?;
+        return Ok(());
+    }
+
+    send_paginated(ctx, pages).await
+}
+
+async fn send_as_file(ctx: Context<'_>, row_count: usize, csv_text: String) -> Result<(), Error> {
+    let attachment = serenity::CreateAttachment::bytes(csv_text.into_bytes(), "results.csv");
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!("Result has {} rows, attached as a file", row_count))
+            .attachment(attachment),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn send_paginated(ctx: Context<'_>, pages: Vec<String>) -> Result<(), Error> {
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{}prev", ctx_id);
+    let next_button_id = format!("{}next", ctx_id);
+
+    let mut page = 0usize;
+    let page_content = |page: usize| format!("Page {}/{}\n{}", page + 1, pages.len(), pages[page]);
+    let buttons = |page: usize| {
+        serenity::CreateActionRow::Buttons(vec![
+            serenity::CreateButton::new(&prev_button_id)
+                .emoji('◀')
+                .disabled(page == 0),
+            serenity::CreateButton::new(&next_button_id)
+                .emoji('▶')
+                .disabled(page == pages.len() - 1),
+        ])
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(page_content(page))
+            .components(vec![buttons(page)]),
+    )
+    .await?;
+
+    let filter_prev_id = prev_button_id.clone();
+    let filter_next_id = next_button_id.clone();
+    while let Some(press) = serenity::ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id == filter_prev_id || press.data.custom_id == filter_next_id)
+        .timeout(Duration::from_secs(600))
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            page = (page + 1).min(pages.len() - 1);
+        } else {
+            page = page.saturating_sub(1);
+        }
+
+        press
+            .create_response(
+                ctx,
+                serenity::CreateInteractionResponse::UpdateMessage(
+                    serenity::CreateInteractionResponseMessage::new()
+                        .content(page_content(page))
+                        .components(vec![buttons(page)]),
+                ),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Vec<String> {
+        vec!["a".to_string()]
+    }
+
+    fn rows(n: usize) -> Vec<Vec<String>> {
+        (0..n).map(|i| vec![i.to_string()]).collect()
+    }
+
+    #[test]
+    fn test_paginate_fits_on_one_page() {
+        let pages = paginate(&header(), &rows(5)).unwrap();
+        assert_eq!(pages.len(), 1);
+    }
+
+    #[test]
+    fn test_paginate_splits_oversized_tables() {
+        // Each row is small, but there are enough of them that the whole
+        // table can't fit in a single 2000-character message.
+        let pages = paginate(&header(), &rows(2000)).unwrap();
+        assert!(pages.len() > 1);
+        for page in &pages {
+            assert!(page.len() <= DISCORD_MESSAGE_LIMIT);
+        }
+    }
+
+    #[test]
+    fn test_paginate_empty_rows_renders_header_only() {
+        let pages = paginate(&header(), &[]).unwrap();
+        assert_eq!(pages.len(), 1);
+    }
+
+    #[test]
+    fn test_paginate_single_oversized_row_is_its_own_page() {
+        let wide_row = vec![vec!["x".repeat(DISCORD_MESSAGE_LIMIT * 2)]];
+        let pages = paginate(&header(), &wide_row).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].len() > DISCORD_MESSAGE_LIMIT);
+    }
+}
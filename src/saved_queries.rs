@@ -0,0 +1,147 @@
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use regex::Regex;
+use rusqlite::params;
+
+use crate::Error;
+
+/// Named query templates a user can save and later invoke with arguments,
+/// e.g. `SELECT * FROM nxthdr.bgp_updates WHERE asn = {asn}`. Shares its
+/// SQLite database with [`crate::history::HistoryStore`].
+#[derive(Clone)]
+pub struct SavedQueryStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl SavedQueryStore {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Result<Self, Error> {
+        pool.get()?.execute(
+            "CREATE TABLE IF NOT EXISTS saved_queries (
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                query TEXT NOT NULL,
+                PRIMARY KEY (user_id, name)
+            )",
+            [],
+        )?;
+        Ok(Self { pool })
+    }
+
+    /// Saves `query` as `name`, overwriting any existing template of the
+    /// same name for `user_id`.
+    pub fn save(&self, user_id: u64, name: &str, query: &str) -> Result<(), Error> {
+        self.pool.get()?.execute(
+            "INSERT INTO saved_queries (user_id, name, query) VALUES (?1, ?2, ?3)
+             ON CONFLICT(user_id, name) DO UPDATE SET query = excluded.query",
+            params![user_id as i64, name, query],
+        )?;
+        Ok(())
+    }
+
+    pub fn unsave(&self, user_id: u64, name: &str) -> Result<bool, Error> {
+        let deleted = self.pool.get()?.execute(
+            "DELETE FROM saved_queries WHERE user_id = ?1 AND name = ?2",
+            params![user_id as i64, name],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get(&self, user_id: u64, name: &str) -> Result<Option<String>, Error> {
+        let query = self
+            .pool
+            .get()?
+            .query_row(
+                "SELECT query FROM saved_queries WHERE user_id = ?1 AND name = ?2",
+                params![user_id as i64, name],
+                |row| row.get(0),
+            )
+            .ok();
+        Ok(query)
+    }
+
+    pub fn list(&self, user_id: u64) -> Result<Vec<(String, String)>, Error> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare("SELECT name, query FROM saved_queries WHERE user_id = ?1 ORDER BY name")?;
+        let rows = stmt
+            .query_map(params![user_id as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+}
+
+/// Substitutes `{key}` placeholders in `template` with the matching value
+/// from `args`, then rejects the result if any `{...}` placeholder was left
+/// unfilled.
+pub fn substitute(template: &str, args: &[(String, String)]) -> Result<String, Error> {
+    let mut query = template.to_string();
+    for (key, value) in args {
+        query = query.replace(&format!("{{{}}}", key), value);
+    }
+
+    let placeholder = Regex::new(r"\{[a-zA-Z0-9_]+\}").unwrap();
+    if placeholder.is_match(&query) {
+        return Err(format!("Unfilled placeholder in `{}`", query).into());
+    }
+
+    Ok(query)
+}
+
+/// Parses `k=v` pairs separated by whitespace, as accepted by `/run`.
+pub fn parse_args(args: &str) -> Result<Vec<(String, String)>, Error> {
+    args.split_whitespace()
+        .map(|pair| {
+            pair.split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .ok_or_else(|| format!("`{}` is not a `key=value` pair", pair).into())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute_fills_placeholders() {
+        assert_eq!(
+            substitute(
+                "SELECT * FROM nxthdr.bgp_updates WHERE asn = {asn}",
+                &[("asn".to_string(), "13335".to_string())]
+            )
+            .unwrap(),
+            "SELECT * FROM nxthdr.bgp_updates WHERE asn = 13335"
+        );
+    }
+
+    #[test]
+    fn test_substitute_rejects_unfilled_placeholder() {
+        assert!(substitute("SELECT * FROM nxthdr.bgp_updates WHERE asn = {asn}", &[]).is_err());
+    }
+
+    #[test]
+    fn test_substitute_ignores_unused_args() {
+        assert_eq!(
+            substitute(
+                "SELECT 1",
+                &[("asn".to_string(), "13335".to_string())]
+            )
+            .unwrap(),
+            "SELECT 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            parse_args("asn=13335 proto=bgp").unwrap(),
+            vec![
+                ("asn".to_string(), "13335".to_string()),
+                ("proto".to_string(), "bgp".to_string()),
+            ]
+        );
+
+        assert_eq!(parse_args("").unwrap(), Vec::<(String, String)>::new());
+
+        assert!(parse_args("asn").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude as serenity;
+use reqwest::Client;
+use tokio::sync::{Mutex, Notify};
+use tracing::{error, info};
+
+use crate::pagination;
+use crate::{do_query, format_query, Error};
+
+/// A saved query that gets re-run on a fixed interval and posted to a channel.
+#[derive(Clone)]
+pub struct Subscription {
+    pub id: u64,
+    pub channel_id: serenity::ChannelId,
+    pub query: String,
+    pub interval: Duration,
+}
+
+#[derive(Default)]
+struct Schedule {
+    by_next_run: BTreeMap<Instant, Vec<Subscription>>,
+    next_id: u64,
+}
+
+/// Shared state for the subscription subsystem: the time-ordered queue of
+/// pending runs, plus a `Notify` so the background loop wakes up as soon as
+/// a subscription is added instead of waiting out its current sleep.
+#[derive(Default)]
+pub struct Subscriptions {
+    schedule: Mutex<Schedule>,
+    notify: Notify,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn add(&self, channel_id: serenity::ChannelId, query: String, interval: Duration) -> u64 {
+        let mut schedule = self.schedule.lock().await;
+        let id = schedule.next_id;
+        schedule.next_id += 1;
+        let sub = Subscription {
+            id,
+            channel_id,
+            query,
+            interval,
+        };
+        schedule
+            .by_next_run
+            .entry(Instant::now() + interval)
+            .or_default()
+            .push(sub);
+        drop(schedule);
+        self.notify.notify_one();
+        id
+    }
+
+    pub async fn remove(&self, id: u64) -> bool {
+        let mut schedule = self.schedule.lock().await;
+        for subs in schedule.by_next_run.values_mut() {
+            if let Some(pos) = subs.iter().position(|s| s.id == id) {
+                subs.remove(pos);
+                return true;
+            }
+        }
+        false
+    }
+
+    pub async fn list(&self) -> Vec<Subscription> {
+        let schedule = self.schedule.lock().await;
+        schedule.by_next_run.values().flatten().cloned().collect()
+    }
+}
+
+/// Background task: repeatedly runs whichever subscriptions are due, then
+/// sleeps until the next one is, waking early if a new subscription arrives.
+/// If the bot was asleep past one or more intervals, a run is never
+/// back-filled: the next run is always scheduled from `now`, not from the
+/// missed deadline.
+pub async fn run_subscriptions(
+    http: Arc<serenity::Http>,
+    client: Client,
+    subscriptions: Arc<Subscriptions>,
+    url: String,
+    output_limit: i32,
+) {
+    loop {
+        let next_run = {
+            let schedule = subscriptions.schedule.lock().await;
+            schedule.by_next_run.keys().next().copied()
+        };
+
+        let due = match next_run {
+            None => {
+                subscriptions.notify.notified().await;
+                continue;
+            }
+            Some(next_run) if next_run > Instant::now() => {
+                tokio::select! {
+                    _ = tokio::time::sleep(next_run - Instant::now()) => {},
+                    _ = subscriptions.notify.notified() => {},
+                }
+                continue;
+            }
+            Some(next_run) => {
+                let mut schedule = subscriptions.schedule.lock().await;
+                schedule.by_next_run.remove(&next_run).unwrap_or_default()
+            }
+        };
+
+        for sub in due {
+            run_one(&http, &client, &sub, &url, output_limit).await;
+            let mut schedule = subscriptions.schedule.lock().await;
+            schedule
+                .by_next_run
+                .entry(Instant::now() + sub.interval)
+                .or_default()
+                .push(sub);
+        }
+    }
+}
+
+async fn run_one(http: &serenity::Http, client: &Client, sub: &Subscription, url: &str, output_limit: i32) {
+    let result: Result<(String, pagination::Rendered), Error> = async {
+        let formatted = format_query(sub.query.clone(), output_limit).await?;
+        let (resp, _duration) = do_query(client, url.to_string(), formatted).await?;
+        let csv_text = resp.text().await?;
+        let rendered = pagination::render(&csv_text)?;
+        Ok((csv_text, rendered))
+    }
+    .await;
+
+    let post_result = match result {
+        Ok((csv_text, pagination::Rendered::TooLargeForPages { row_count })) => {
+            let attachment = serenity::CreateAttachment::bytes(csv_text.into_bytes(), "results.csv");
+            let message = serenity::CreateMessage::new().content(format!(
+                "Subscription `{}`: {} rows, attached as a file",
+                sub.query, row_count
+            ));
+            sub.channel_id.send_files(http, [attachment], message).await.map(|_| ())
+        }
+        Ok((_, pagination::Rendered::Pages(pages))) => post_pages(http, sub, &pages).await,
+        Err(e) => {
+            error!("subscription {} (`{}`) failed: {}", sub.id, sub.query, e);
+            sub.channel_id
+                .say(http, format!("Subscription `{}` failed: {}", sub.query, e))
+                .await
+                .map(|_| ())
+        }
+    };
+
+    match post_result {
+        Ok(()) => info!("posted subscription {} to channel {}", sub.id, sub.channel_id),
+        Err(e) => error!("failed to post subscription {} result: {}", sub.id, e),
+    }
+}
+
+async fn post_pages(http: &serenity::Http, sub: &Subscription, pages: &[String]) -> serenity::Result<()> {
+    for (n, page) in pages.iter().enumerate() {
+        let content = if pages.len() > 1 {
+            format!("Subscription `{}` (page {}/{}):\n{}", sub.query, n + 1, pages.len(), page)
+        } else {
+            format!("Subscription `{}`:\n{}", sub.query, page)
+        };
+        sub.channel_id.say(http, content).await?;
+    }
+    Ok(())
+}